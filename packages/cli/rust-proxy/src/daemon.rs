@@ -1,33 +1,83 @@
+use sha2::{Sha256, Digest};
 use std::env;
 use std::fs::File;
 use std::os::unix::net::UnixStream;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::thread;
 use std::time::Duration;
 
-const MAX_CONNECT_ATTEMPTS: u32 = 20;
-const CONNECT_RETRY_MS: u64 = 50;
+use crate::transport::{self, Connection, RemoteDaemon};
+
+/// Wrap a connected Unix socket as a `Connection` with independent
+/// read/write handles, so a blocking read never holds up a concurrent write
+/// (see `transport::Connection`).
+fn unix_connection(stream: UnixStream) -> Result<Connection, String> {
+    let writer = stream
+        .try_clone()
+        .map_err(|e| format!("Failed to clone daemon socket: {}", e))?;
+    Ok(Connection::split(Box::new(stream), Box::new(writer)))
+}
+
+pub(crate) const MAX_CONNECT_ATTEMPTS: u32 = 20;
+pub(crate) const CONNECT_RETRY_MS: u64 = 50;
 
 // ---------------------------------------------------------------------------
-// DaemonPaths — global daemon at ~/.max/
+// DaemonPaths — per-project paths under ~/.max/daemons/<hash>/
 // ---------------------------------------------------------------------------
 
-struct DaemonPaths {
-    dir: PathBuf,
-    socket: PathBuf,
-    pid: PathBuf,
-    log: PathBuf,
+pub struct DaemonPaths {
+    pub dir: PathBuf,
+    pub socket: PathBuf,
+    pub pid: PathBuf,
+    pub log: PathBuf,
+}
+
+fn project_hash(project_root: &Path) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(project_root.to_string_lossy().as_bytes());
+    let result = hasher.finalize();
+    result.iter().take(6).map(|b| format!("{:02x}", b)).collect()
+}
+
+impl DaemonPaths {
+    pub fn for_project(project_root: &Path) -> Self {
+        let hash = project_hash(project_root);
+        Self::for_dir(daemons_dir().join(&hash))
+    }
+
+    /// Build paths from an already-known `~/.max/daemons/<hash>/` directory,
+    /// e.g. one discovered by scanning the daemons directory.
+    pub(crate) fn for_dir(dir: PathBuf) -> Self {
+        DaemonPaths {
+            socket: dir.join("daemon.sock"),
+            pid: dir.join("daemon.pid"),
+            log: dir.join("daemon.log"),
+            dir,
+        }
+    }
 }
 
-fn global_daemon_paths() -> DaemonPaths {
+/// `~/.max/daemons/` — the parent of every per-project daemon directory.
+pub(crate) fn daemons_dir() -> PathBuf {
     let home = env::var("HOME").expect("HOME not set");
-    let dir = PathBuf::from(home).join(".max");
-    DaemonPaths {
-        socket: dir.join("daemon.sock"),
-        pid: dir.join("daemon.pid"),
-        log: dir.join("daemon.log"),
-        dir,
+    PathBuf::from(home).join(".max").join("daemons")
+}
+
+// ---------------------------------------------------------------------------
+// Project root discovery
+// ---------------------------------------------------------------------------
+
+/// Walk up from start_dir looking for .max/ + max.json (matches Bun-side algorithm).
+pub fn find_project_root(start_dir: &Path) -> Option<PathBuf> {
+    let mut dir = start_dir.canonicalize().ok()?;
+    loop {
+        if dir.join("max.json").exists() && dir.join(".max").is_dir() {
+            return Some(dir);
+        }
+        if !dir.pop() {
+            return None;
+        }
     }
 }
 
@@ -68,7 +118,7 @@ pub fn find_daemon_script() -> Result<String, String> {
         .map_err(|_| "Cannot find daemon script. Set MAX_DAEMON env var.".to_string())
 }
 
-fn is_daemon_alive(paths: &DaemonPaths) -> bool {
+pub(crate) fn is_daemon_alive(paths: &DaemonPaths) -> bool {
     let pid_str = match std::fs::read_to_string(&paths.pid) {
         Ok(s) => s,
         Err(_) => return false,
@@ -81,12 +131,12 @@ fn is_daemon_alive(paths: &DaemonPaths) -> bool {
     unsafe { kill(pid, 0) == 0 }
 }
 
-fn clean_stale_files(paths: &DaemonPaths) {
+pub(crate) fn clean_stale_files(paths: &DaemonPaths) {
     let _ = std::fs::remove_file(&paths.socket);
     let _ = std::fs::remove_file(&paths.pid);
 }
 
-fn spawn(paths: &DaemonPaths) -> Result<(), String> {
+pub fn spawn(project_root: &Path, paths: &DaemonPaths) -> Result<(), String> {
     let script = find_daemon_script()?;
     let dev = is_dev_mode();
 
@@ -94,6 +144,14 @@ fn spawn(paths: &DaemonPaths) -> Result<(), String> {
     std::fs::create_dir_all(&paths.dir)
         .map_err(|e| format!("Failed to create daemon dir: {}", e))?;
 
+    // Write project.json for discoverability
+    let project_json = format!(
+        r#"{{"root":"{}"}}"#,
+        project_root.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"")
+    );
+    std::fs::write(paths.dir.join("project.json"), &project_json)
+        .map_err(|e| format!("Failed to write project.json: {}", e))?;
+
     if dev {
         eprintln!("\x1b[33mStarting daemon in watch mode\x1b[0m");
     }
@@ -110,6 +168,9 @@ fn spawn(paths: &DaemonPaths) -> Result<(), String> {
 
     cmd.arg(&script)
         .arg("--daemonized")
+        .arg("--project-root")
+        .arg(project_root.as_os_str())
+        .current_dir(project_root)
         .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::from(log_file))
@@ -119,28 +180,46 @@ fn spawn(paths: &DaemonPaths) -> Result<(), String> {
     Ok(())
 }
 
-pub fn connect() -> Result<UnixStream, String> {
-    let paths = global_daemon_paths();
+/// A remote daemon, configured via `MAX_DAEMON_URL` or the project's
+/// `max.json` `"daemon"` field (e.g. `"daemon": "max://host:4000"`).
+fn configured_remote(project_root: &Path) -> Option<RemoteDaemon> {
+    if let Ok(url) = env::var("MAX_DAEMON_URL") {
+        if let Some(remote) = RemoteDaemon::parse(&url) {
+            return Some(remote);
+        }
+    }
+
+    let contents = std::fs::read_to_string(project_root.join("max.json")).ok()?;
+    let config: serde_json::Value = serde_json::from_str(&contents).ok()?;
+    RemoteDaemon::parse(config.get("daemon")?.as_str()?)
+}
+
+pub fn connect(project_root: &Path) -> Result<Connection, String> {
+    if let Some(remote) = configured_remote(project_root) {
+        return transport::connect(&remote);
+    }
+
+    let paths = DaemonPaths::for_project(project_root);
 
     if let Ok(stream) = UnixStream::connect(&paths.socket) {
-        return Ok(stream);
+        return unix_connection(stream);
     }
 
     if !is_daemon_alive(&paths) {
         clean_stale_files(&paths);
-        spawn(&paths)?;
+        spawn(project_root, &paths)?;
     }
 
     for attempt in 0..MAX_CONNECT_ATTEMPTS {
         thread::sleep(Duration::from_millis(CONNECT_RETRY_MS));
 
         match UnixStream::connect(&paths.socket) {
-            Ok(stream) => return Ok(stream),
+            Ok(stream) => return unix_connection(stream),
             Err(_) if attempt < MAX_CONNECT_ATTEMPTS - 1 => continue,
             Err(e) => {
                 return Err(format!(
-                    "Failed to connect after {} attempts: {}, {}",
-                    MAX_CONNECT_ATTEMPTS, e, paths.socket.display()
+                    "Failed to connect after {} attempts: {}",
+                    MAX_CONNECT_ATTEMPTS, e
                 ))
             }
         }
@@ -148,3 +227,52 @@ pub fn connect() -> Result<UnixStream, String> {
 
     Err("Failed to connect to daemon".to_string())
 }
+
+/// Force-restart the local daemon for `project_root` and reconnect. Used
+/// when the client detects a `protocolVersion` mismatch with a stale daemon
+/// left over from a previous version of this binary.
+///
+/// A project configured for a remote daemon (`configured_remote`) isn't
+/// ours to restart — there's no local process to kill, and silently
+/// spawning an unrelated local daemon would switch the project from the
+/// team's shared remote daemon to a local one without telling anyone. Fail
+/// loudly instead.
+pub fn restart_and_reconnect(project_root: &Path) -> Result<Connection, String> {
+    if let Some(remote) = configured_remote(project_root) {
+        return Err(format!(
+            "Remote daemon at {}:{} reported a protocol version mismatch; \
+             it needs to be upgraded on its end, not restarted locally.",
+            remote.host, remote.port
+        ));
+    }
+
+    let paths = DaemonPaths::for_project(project_root);
+
+    if let Ok(pid_str) = std::fs::read_to_string(&paths.pid) {
+        if let Ok(pid) = pid_str.trim().parse::<i32>() {
+            extern "C" { fn kill(pid: i32, sig: i32) -> i32; }
+            const SIGTERM: i32 = 15;
+            unsafe { kill(pid, SIGTERM); }
+        }
+    }
+
+    clean_stale_files(&paths);
+    spawn(project_root, &paths)?;
+
+    for attempt in 0..MAX_CONNECT_ATTEMPTS {
+        thread::sleep(Duration::from_millis(CONNECT_RETRY_MS));
+
+        match UnixStream::connect(&paths.socket) {
+            Ok(stream) => return unix_connection(stream),
+            Err(_) if attempt < MAX_CONNECT_ATTEMPTS - 1 => continue,
+            Err(e) => {
+                return Err(format!(
+                    "Failed to reconnect after restart ({} attempts): {}",
+                    MAX_CONNECT_ATTEMPTS, e
+                ))
+            }
+        }
+    }
+
+    Err("Failed to reconnect to daemon after restart".to_string())
+}
@@ -1,9 +1,41 @@
 mod daemon;
+mod manage;
+mod raw_shell;
+mod transport;
 
 use std::env;
 use std::io::{self, BufRead, IsTerminal, Write};
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use transport::Connection;
+
+/// Bumped whenever the JSONL message shapes exchanged with the daemon
+/// change. Sent on every first request so a stale daemon from a previous
+/// version can be detected and restarted instead of silently misbehaving.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// True if `msg` indicates the connected daemon doesn't speak our protocol.
+/// A daemon that knows about the handshake says so explicitly via
+/// `{"kind":"version-mismatch"}`. But the daemon this feature exists to
+/// catch — one from before the handshake existed at all — has no way to
+/// know to emit that sentinel; it just answers with whatever its old
+/// protocol produces. So on the first message of a session, also treat a
+/// missing or differing `protocolVersion` field as a mismatch.
+pub(crate) fn is_version_mismatch(msg: &serde_json::Value, first_message: bool) -> bool {
+    if msg["kind"].as_str() == Some("version-mismatch") {
+        return true;
+    }
+    if !first_message {
+        return false;
+    }
+    msg.get("protocolVersion")
+        .and_then(|v| v.as_u64())
+        .map(|v| v != PROTOCOL_VERSION as u64)
+        .unwrap_or(true)
+}
 
 fn run_direct(args: &[String], project_root: Option<&Path>) {
     let script = match daemon::find_daemon_script() {
@@ -42,17 +74,211 @@ fn should_use_color(args: &[String]) -> bool {
     io::stdout().is_terminal()
 }
 
+/// Pulls `--format <value>` out of `args` so it doesn't get forwarded as a
+/// positional to the wrapped command.
+fn extract_format(args: &mut Vec<String>) -> Option<String> {
+    let idx = args.iter().position(|a| a == "--format")?;
+    args.remove(idx);
+    if idx < args.len() {
+        Some(args.remove(idx))
+    } else {
+        None
+    }
+}
+
+/// In `--format json` mode, a fatal error becomes a structured record on
+/// stdout (exit non-zero) instead of a colored line on stderr, so wrappers
+/// and editor integrations can parse it deterministically.
+fn fail(json_mode: bool, message: &str) -> ! {
+    if json_mode {
+        println!("{}", serde_json::json!({ "kind": "error", "message": message }));
+    } else {
+        eprintln!("\x1b[31m{}\x1b[0m", message);
+    }
+    std::process::exit(1);
+}
+
+// ---------------------------------------------------------------------------
+// Signal forwarding — relay Ctrl-C/TERM/window-resize to the daemon so a
+// long-running command behaves like a normal foreground process instead of
+// only killing this thin client.
+// ---------------------------------------------------------------------------
+
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+const SIGWINCH: i32 = 28;
+const TIOCGWINSZ: u64 = 0x5413;
+
+#[repr(C)]
+struct Winsize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+extern "C" {
+    fn signal(signum: i32, handler: usize) -> usize;
+    fn pipe(fds: *mut i32) -> i32;
+    fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+    fn read(fd: i32, buf: *mut u8, count: usize) -> isize;
+    fn close(fd: i32) -> i32;
+    fn ioctl(fd: i32, request: u64, argp: *mut std::ffi::c_void) -> i32;
+}
+
+/// Write end of the self-pipe: signal handlers only write a single byte here,
+/// since they must stay async-signal-safe and the main thread is blocked in
+/// `read_line`.
+static SIGNAL_PIPE_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+fn terminal_size() -> (u16, u16) {
+    let mut ws = Winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    let rc = unsafe {
+        ioctl(
+            io::stdout().as_raw_fd(),
+            TIOCGWINSZ,
+            &mut ws as *mut Winsize as *mut std::ffi::c_void,
+        )
+    };
+    if rc == 0 && ws.ws_row != 0 && ws.ws_col != 0 {
+        (ws.ws_row, ws.ws_col)
+    } else {
+        (0, 0)
+    }
+}
+
+extern "C" fn handle_signal(signum: i32) {
+    let fd = SIGNAL_PIPE_WRITE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        let byte = signum as u8;
+        unsafe { write(fd, &byte, 1); }
+    }
+}
+
+/// Spawn a thread that turns SIGINT/SIGTERM/SIGWINCH into conversational
+/// protocol messages and writes them to `writer` (a clone of the daemon
+/// socket, since the main thread owns the original for the read loop).
+///
+/// Called again on every handshake retry after a daemon restart, so it must
+/// tear down the previous forwarder first: closing its write end unblocks
+/// that forwarder's thread (its `read` sees EOF), which then closes its own
+/// read end and exits — otherwise the old thread and both of its fds would
+/// leak every time this is called.
+pub(crate) fn install_signal_forwarding(mut writer: Connection) {
+    let mut fds = [0i32; 2];
+    if unsafe { pipe(fds.as_mut_ptr()) } != 0 {
+        return;
+    }
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+
+    let old_write_fd = SIGNAL_PIPE_WRITE.swap(write_fd, Ordering::Relaxed);
+    if old_write_fd >= 0 {
+        unsafe { close(old_write_fd); }
+    }
+
+    unsafe {
+        signal(SIGINT, handle_signal as *const () as usize);
+        signal(SIGTERM, handle_signal as *const () as usize);
+        signal(SIGWINCH, handle_signal as *const () as usize);
+    }
+
+    std::thread::spawn(move || {
+        loop {
+            let mut byte = [0u8; 1];
+            let n = unsafe { read(read_fd, byte.as_mut_ptr(), 1) };
+            if n <= 0 {
+                break;
+            }
+
+            let msg = match byte[0] as i32 {
+                SIGINT => serde_json::json!({ "kind": "signal", "signal": "SIGINT" }),
+                SIGTERM => serde_json::json!({ "kind": "signal", "signal": "SIGTERM" }),
+                SIGWINCH => {
+                    let (rows, cols) = terminal_size();
+                    serde_json::json!({ "kind": "resize", "rows": rows, "cols": cols })
+                }
+                _ => continue,
+            };
+
+            let _ = writer
+                .write_all(msg.to_string().as_bytes())
+                .and_then(|_| writer.write_all(b"\n"));
+        }
+        unsafe { close(read_fd); }
+    });
+}
+
 fn main() {
     let mut args: Vec<String> = env::args().skip(1).collect();
     let cwd = env::current_dir().expect("Cannot determine CWD");
     let project_root = daemon::find_project_root(&cwd);
     let use_color = should_use_color(&args);
+    let format = extract_format(&mut args);
+    let json_mode = format.as_deref() == Some("json");
 
-    // daemon subcommand — always run direct (bypasses socket)
+    // daemon subcommand — fleet management (list/stop/restart/logs) is
+    // handled entirely in this client; anything else falls through to the
+    // Bun side, which bypasses the socket.
     if args.first().map(|s| s == "daemon").unwrap_or(false) {
+        match args.get(1).map(String::as_str) {
+            Some("list") | Some("status") => {
+                manage::list();
+                return;
+            }
+            Some("stop") => {
+                manage::stop(&args[2..], project_root.as_deref());
+                return;
+            }
+            Some("restart") => {
+                manage::restart(&args[2..], project_root.as_deref());
+                return;
+            }
+            Some("logs") => {
+                manage::logs(&args[2..], project_root.as_deref());
+                return;
+            }
+            _ => {}
+        }
         run_direct(&args, project_root.as_deref());
     }
 
+    // shell subcommand — raw PTY mode, bypasses the line-oriented protocol
+    if args.first().map(|s| s == "shell").unwrap_or(false) {
+        args.remove(0);
+        let cmd = if !args.is_empty() { Some(args.remove(0)) } else { None };
+
+        let project_root = match &project_root {
+            Some(root) => root.clone(),
+            None => {
+                eprintln!("max shell: no project found (no max.json/.max in this directory tree)");
+                std::process::exit(1);
+            }
+        };
+
+        let (rows, cols) = terminal_size();
+        let mut req = serde_json::json!({
+            "kind": "shell",
+            "cwd": cwd.to_string_lossy(),
+            "color": use_color,
+            "rows": rows,
+            "cols": cols,
+            "protocolVersion": PROTOCOL_VERSION
+        });
+        if let Some(ref c) = cmd {
+            req["cmd"] = serde_json::json!(c);
+        }
+        if let Some(ref f) = format {
+            req["format"] = serde_json::json!(f);
+        }
+
+        let stream = match daemon::connect(&project_root) {
+            Ok(s) => s,
+            Err(e) => fail(json_mode, &format!("Daemon not responding ({})", e)),
+        };
+
+        std::process::exit(raw_shell::run(&project_root, stream, &req));
+    }
+
     // No project found — run direct (handles init, non-project commands)
     let project_root = match project_root {
         Some(root) => root,
@@ -74,118 +300,169 @@ fn main() {
         ("run", None)
     };
 
+    let (term_rows, term_cols) = terminal_size();
+
     let mut req = serde_json::json!({
         "kind": kind,
         "argv": args,
         "cwd": cwd.to_string_lossy(),
-        "color": use_color
+        "color": use_color,
+        "rows": term_rows,
+        "cols": term_cols,
+        "protocolVersion": PROTOCOL_VERSION
     });
     if let Some(ref s) = shell {
         req["shell"] = serde_json::json!(s);
     }
+    if let Some(ref f) = format {
+        req["format"] = serde_json::json!(f);
+    }
 
-    // Try daemon socket; fall back to direct mode
+    // Try daemon socket; fall back to direct mode — except in --format
+    // json, where a silent direct-exec would inherit stdio and could hang
+    // waiting on input instead of producing a parseable error record.
     let mut stream = match daemon::connect(&project_root) {
         Ok(s) => s,
         Err(e) => {
+            if json_mode {
+                fail(json_mode, &format!("Daemon not responding ({})", e));
+            }
             eprintln!("\x1b[31mDaemon not responding ({})\x1b[0m", e);
             run_direct(&args, Some(&project_root));
             return;
         }
     };
 
-    if let Err(e) = stream.write_all(req.to_string().as_bytes())
-        .and_then(|_| stream.write_all(b"\n"))
-    {
-        eprintln!("Error writing to socket: {}", e);
-        std::process::exit(1);
-    }
+    // Retried once if the daemon turns out to be a stale, incompatible
+    // version — restarted transparently and the request resent.
+    let mut restarted = false;
 
-    // Conversational protocol: read JSONL messages in a loop.
-    // Messages are newline-delimited JSON objects.
-    let mut reader = io::BufReader::new(&mut stream);
+    'session: loop {
+        let signal_writer = stream.clone();
+        install_signal_forwarding(signal_writer);
 
-    loop {
-        let mut line = String::new();
-        match reader.read_line(&mut line) {
-            Ok(0) => {
-                // EOF — daemon closed without a response
-                eprintln!("Daemon closed connection unexpectedly");
-                std::process::exit(1);
-            }
-            Err(e) => {
-                eprintln!("Error reading from socket: {}", e);
-                std::process::exit(1);
-            }
-            Ok(_) => {}
+        if let Err(e) = stream.write_all(req.to_string().as_bytes())
+            .and_then(|_| stream.write_all(b"\n"))
+        {
+            fail(json_mode, &format!("Error writing to socket: {}", e));
         }
 
-        let msg: serde_json::Value = match serde_json::from_str(line.trim()) {
-            Ok(v) => v,
-            Err(e) => {
-                eprintln!("Error parsing message: {}", e);
-                std::process::exit(1);
-            }
-        };
+        // Conversational protocol: read JSONL messages in a loop.
+        // Messages are newline-delimited JSON objects.
+        let mut reader = io::BufReader::new(stream.clone());
+        let mut first_message = true;
 
-        match msg["kind"].as_str() {
-            Some("prompt") => {
-                // Display the prompt message and read input from the real terminal
-                if let Some(message) = msg["message"].as_str() {
-                    print!("{}", message);
-                    let _ = io::stdout().flush();
+        loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => {
+                    // EOF — daemon closed without a response
+                    fail(json_mode, "Daemon closed connection unexpectedly");
                 }
-                let mut input = String::new();
-                if let Err(e) = io::stdin().read_line(&mut input) {
-                    eprintln!("Error reading input: {}", e);
-                    std::process::exit(1);
-                }
-                let input_msg = serde_json::json!({ "kind": "input", "value": input.trim_end_matches('\n') });
-                // Write back to the socket (need mutable access via the underlying stream)
-                let writer = reader.get_mut();
-                if let Err(e) = writer.write_all(input_msg.to_string().as_bytes())
-                    .and_then(|_| writer.write_all(b"\n"))
-                {
-                    eprintln!("Error writing input to socket: {}", e);
-                    std::process::exit(1);
+                Err(e) => {
+                    fail(json_mode, &format!("Error reading from socket: {}", e));
                 }
+                Ok(_) => {}
             }
-            Some("write") => {
-                // Intermediate output — display to the user
-                if let Some(text) = msg["text"].as_str() {
-                    print!("{}", text);
-                    let _ = io::stdout().flush();
+
+            let msg: serde_json::Value = match serde_json::from_str(line.trim()) {
+                Ok(v) => v,
+                Err(e) => {
+                    fail(json_mode, &format!("Error parsing message: {}", e));
                 }
+            };
+
+            if is_version_mismatch(&msg, first_message) {
+                if restarted {
+                    fail(json_mode, "Daemon protocol version mismatch persists after restart, giving up.");
+                }
+                eprintln!("Restarting daemon for a protocol version mismatch...");
+                stream = match daemon::restart_and_reconnect(&project_root) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        fail(json_mode, &format!("Failed to restart daemon: {}", e));
+                    }
+                };
+                restarted = true;
+                continue 'session;
             }
-            Some("response") => {
-                // Final response — handle completions or standard output
-                if kind == "complete" {
-                    if let Some(output) = msg["completionOutput"].as_str() {
-                        print!("{}", output);
-                        return;
+            first_message = false;
+
+            // In --format json mode, every message is echoed verbatim as a
+            // compact JSON line instead of being unwrapped to human text.
+            if json_mode {
+                println!("{}", msg);
+            }
+
+            match msg["kind"].as_str() {
+                Some("prompt") => {
+                    // Display the prompt message and read input from the real terminal
+                    if let Some(message) = msg["message"].as_str() {
+                        if !json_mode {
+                            print!("{}", message);
+                            let _ = io::stdout().flush();
+                        }
                     }
-                    if let Some(arr) = msg["completions"].as_array() {
-                        for v in arr {
-                            if let Some(s) = v.as_str() {
-                                println!("{}", s);
-                            }
+                    let mut input = String::new();
+                    if let Err(e) = io::stdin().read_line(&mut input) {
+                        fail(json_mode, &format!("Error reading input: {}", e));
+                    }
+                    let input_msg = serde_json::json!({ "kind": "input", "value": input.trim_end_matches('\n') });
+                    // Write back to the socket (need mutable access via the underlying stream)
+                    let writer = reader.get_mut();
+                    if let Err(e) = writer.write_all(input_msg.to_string().as_bytes())
+                        .and_then(|_| writer.write_all(b"\n"))
+                    {
+                        fail(json_mode, &format!("Error writing input to socket: {}", e));
+                    }
+                }
+                Some("write") => {
+                    // Intermediate output — display to the user
+                    if let Some(text) = msg["text"].as_str() {
+                        if !json_mode {
+                            print!("{}", text);
+                            let _ = io::stdout().flush();
                         }
                     }
-                    return;
                 }
+                Some("response") => {
+                    // Final response — handle completions or standard output
+                    if kind == "complete" {
+                        if let Some(output) = msg["completionOutput"].as_str() {
+                            if !json_mode {
+                                print!("{}", output);
+                            }
+                            return;
+                        }
+                        if let Some(arr) = msg["completions"].as_array() {
+                            if !json_mode {
+                                for v in arr {
+                                    if let Some(s) = v.as_str() {
+                                        println!("{}", s);
+                                    }
+                                }
+                            }
+                        }
+                        return;
+                    }
+
+                    if let Some(out) = msg["stdout"].as_str() {
+                        if !json_mode {
+                            print!("{}", out);
+                        }
+                    }
+                    if let Some(err) = msg["stderr"].as_str() {
+                        if !json_mode {
+                            eprint!("{}", err);
+                        }
+                    }
 
-                if let Some(out) = msg["stdout"].as_str() {
-                    print!("{}", out);
+                    let exit_code = msg["exitCode"].as_i64().unwrap_or(1) as i32;
+                    std::process::exit(exit_code);
                 }
-                if let Some(err) = msg["stderr"].as_str() {
-                    eprint!("{}", err);
+                _ => {
+                    // Unknown message kind — skip
                 }
-
-                let exit_code = msg["exitCode"].as_i64().unwrap_or(1) as i32;
-                std::process::exit(exit_code);
-            }
-            _ => {
-                // Unknown message kind — skip
             }
         }
     }
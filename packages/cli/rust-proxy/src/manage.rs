@@ -0,0 +1,178 @@
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::daemon::{self, DaemonPaths};
+
+/// A daemon discovered under `~/.max/daemons/<hash>/`, with its project
+/// root read back out of the `project.json` the daemon writes on spawn.
+struct DaemonInfo {
+    project_root: String,
+    pid: Option<i32>,
+    alive: bool,
+    uptime: Option<Duration>,
+    paths: DaemonPaths,
+}
+
+fn discover() -> Vec<DaemonInfo> {
+    let entries = match std::fs::read_dir(daemon::daemons_dir()) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut infos = Vec::new();
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if !dir.is_dir() {
+            continue;
+        }
+
+        let project_root = std::fs::read_to_string(dir.join("project.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+            .and_then(|v| v.get("root").and_then(|r| r.as_str()).map(str::to_string))
+            .unwrap_or_else(|| "<unknown project>".to_string());
+
+        let paths = DaemonPaths::for_dir(dir);
+        let pid = std::fs::read_to_string(&paths.pid)
+            .ok()
+            .and_then(|s| s.trim().parse::<i32>().ok());
+        let alive = daemon::is_daemon_alive(&paths) && UnixStream::connect(&paths.socket).is_ok();
+        let uptime = std::fs::metadata(&paths.pid)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| SystemTime::now().duration_since(t).ok());
+
+        infos.push(DaemonInfo { project_root, pid, alive, uptime, paths });
+    }
+    infos
+}
+
+fn format_uptime(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m{}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+/// `max daemon list` (also `status`): scan every project's daemon directory
+/// and print a table of project root, pid, status, uptime, and log path.
+pub fn list() {
+    let infos = discover();
+    if infos.is_empty() {
+        println!("No daemons found.");
+        return;
+    }
+
+    println!("{:<45} {:>8}  {:<8} {:>8}  LOG", "PROJECT", "PID", "STATUS", "UPTIME");
+    for info in &infos {
+        let pid = info.pid.map(|p| p.to_string()).unwrap_or_else(|| "-".to_string());
+        let status = if info.alive { "running" } else { "stopped" };
+        let uptime = info.uptime.map(format_uptime).unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<45} {:>8}  {:<8} {:>8}  {}",
+            info.project_root,
+            pid,
+            status,
+            uptime,
+            info.paths.log.display()
+        );
+    }
+}
+
+/// Picks which discovered daemons `stop`/`restart`/`logs` apply to: `--all`,
+/// an explicit project root passed as an argument, or (by default) the
+/// project the command was run from.
+fn select_targets(args: &[String], project_root: Option<&Path>) -> Vec<DaemonInfo> {
+    let infos = discover();
+
+    if args.iter().any(|a| a == "--all") {
+        return infos;
+    }
+    if let Some(name) = args.iter().find(|a| a.as_str() != "--all") {
+        return infos.into_iter().filter(|i| &i.project_root == name).collect();
+    }
+    if let Some(root) = project_root {
+        let root_str = root.to_string_lossy().to_string();
+        return infos.into_iter().filter(|i| i.project_root == root_str).collect();
+    }
+
+    Vec::new()
+}
+
+fn terminate(info: &DaemonInfo) {
+    if let Some(pid) = info.pid {
+        extern "C" { fn kill(pid: i32, sig: i32) -> i32; }
+        const SIGTERM: i32 = 15;
+        unsafe { kill(pid, SIGTERM); }
+        println!("Stopped {} (pid {})", info.project_root, pid);
+    }
+    daemon::clean_stale_files(&info.paths);
+}
+
+/// `max daemon stop [--all|<project>]`
+pub fn stop(args: &[String], project_root: Option<&Path>) {
+    let targets = select_targets(args, project_root);
+    if targets.is_empty() {
+        eprintln!("No matching daemon found (pass --all, a project path, or run inside a project).");
+        return;
+    }
+    for info in &targets {
+        terminate(info);
+    }
+}
+
+/// `max daemon restart [--all|<project>]`
+pub fn restart(args: &[String], project_root: Option<&Path>) {
+    let targets = select_targets(args, project_root);
+    if targets.is_empty() {
+        eprintln!("No matching daemon found (pass --all, a project path, or run inside a project).");
+        return;
+    }
+    for info in &targets {
+        terminate(info);
+        std::thread::sleep(Duration::from_millis(200));
+        let root = PathBuf::from(&info.project_root);
+        match daemon::spawn(&root, &info.paths) {
+            Ok(()) => println!("Restarted {}", info.project_root),
+            Err(e) => eprintln!("Failed to restart {}: {}", info.project_root, e),
+        }
+    }
+}
+
+/// `max daemon logs [--all|<project>]` — tail the per-project daemon.log.
+/// `--all` tails every matching daemon's log in turn, each under its own
+/// header, rather than silently picking one in readdir order.
+pub fn logs(args: &[String], project_root: Option<&Path>) {
+    let targets = select_targets(args, project_root);
+    if targets.is_empty() {
+        eprintln!("No matching daemon found (pass --all, a project path, or run inside a project).");
+        std::process::exit(1);
+    }
+
+    let multiple = targets.len() > 1;
+    for (i, info) in targets.iter().enumerate() {
+        if multiple {
+            if i > 0 {
+                println!();
+            }
+            println!("==> {} <==", info.project_root);
+        }
+
+        match std::fs::read_to_string(&info.paths.log) {
+            Ok(contents) => {
+                let lines: Vec<&str> = contents.lines().collect();
+                for line in lines.iter().rev().take(200).rev() {
+                    println!("{}", line);
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", info.paths.log.display(), e);
+            }
+        }
+    }
+}
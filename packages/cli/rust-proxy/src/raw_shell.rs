@@ -0,0 +1,224 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::io::{self, BufRead, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use crate::transport::Connection;
+
+// ---------------------------------------------------------------------------
+// Raw terminal mode
+// ---------------------------------------------------------------------------
+
+const TCGETS: u64 = 0x5401;
+const TCSETS: u64 = 0x5402;
+const ICANON: u32 = 0o0000002;
+const ECHO: u32 = 0o0000010;
+const ISIG: u32 = 0o0000001;
+const IXON: u32 = 0o0002000;
+const ICRNL: u32 = 0o0000400;
+const BRKINT: u32 = 0o0000002;
+const INPCK: u32 = 0o0000020;
+const ISTRIP: u32 = 0o0000040;
+const OPOST: u32 = 0o0000001;
+const CS8: u32 = 0o0000060;
+const VMIN: usize = 6;
+const VTIME: usize = 5;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Termios {
+    c_iflag: u32,
+    c_oflag: u32,
+    c_cflag: u32,
+    c_lflag: u32,
+    c_line: u8,
+    c_cc: [u8; 32],
+    c_ispeed: u32,
+    c_ospeed: u32,
+}
+
+extern "C" {
+    fn ioctl(fd: i32, request: u64, argp: *mut std::ffi::c_void) -> i32;
+}
+
+/// RAII guard that puts stdin into raw mode and restores the previous
+/// (cooked) settings when dropped, including on panic or early return.
+struct RawModeGuard {
+    fd: i32,
+    original: Termios,
+}
+
+impl RawModeGuard {
+    fn enable() -> io::Result<Self> {
+        let fd = io::stdin().as_raw_fd();
+        let mut original: Termios = unsafe { std::mem::zeroed() };
+        if unsafe { ioctl(fd, TCGETS, as_void(&mut original)) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_iflag &= !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
+        raw.c_oflag &= !OPOST;
+        raw.c_cflag |= CS8;
+        raw.c_lflag &= !(ECHO | ICANON | ISIG);
+        raw.c_cc[VMIN] = 1;
+        raw.c_cc[VTIME] = 0;
+
+        if unsafe { ioctl(fd, TCSETS, as_void(&mut raw)) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawModeGuard { fd, original })
+    }
+}
+
+fn as_void(termios: &mut Termios) -> *mut std::ffi::c_void {
+    termios as *mut Termios as *mut std::ffi::c_void
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        unsafe {
+            ioctl(self.fd, TCSETS, as_void(&mut self.original));
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Byte streaming over the conversational protocol
+// ---------------------------------------------------------------------------
+
+/// Run `max shell [cmd]`: sends `req`, then puts the local terminal into raw
+/// mode and streams stdin bytes verbatim to the daemon as `stdin` frames and
+/// renders incoming `stdout` frames straight to stdout, unbuffered — no
+/// `read_line` involved, so the daemon's PTY-backed child sees every
+/// keystroke.
+///
+/// Returns the process exit code rather than exiting directly, so the
+/// `RawModeGuard` runs its `Drop` and restores cooked mode before the
+/// caller exits — `std::process::exit` skips destructors on the current
+/// stack, which would otherwise leave the user's terminal unusable.
+pub fn run(project_root: &Path, mut stream: Connection, req: &serde_json::Value) -> i32 {
+    // Same version-mismatch handshake as the non-shell request loop in
+    // main.rs: a stale daemon gets restarted once, transparently, before we
+    // commit to raw terminal mode.
+    let mut restarted = false;
+
+    let (mut reader, pending_line) = loop {
+        crate::install_signal_forwarding(stream.clone());
+
+        if let Err(e) = stream.write_all(req.to_string().as_bytes())
+            .and_then(|_| stream.write_all(b"\n"))
+        {
+            eprintln!("Error writing to socket: {}", e);
+            return 1;
+        }
+
+        let mut reader = io::BufReader::new(stream.clone());
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => {
+                eprintln!("Daemon closed connection unexpectedly");
+                return 1;
+            }
+            Err(e) => {
+                eprintln!("Error reading from socket: {}", e);
+                return 1;
+            }
+            Ok(_) => {}
+        }
+
+        let msg: serde_json::Value = match serde_json::from_str(line.trim()) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Error parsing message: {}", e);
+                return 1;
+            }
+        };
+
+        if crate::is_version_mismatch(&msg, true) {
+            if restarted {
+                eprintln!("Daemon protocol version mismatch persists after restart, giving up.");
+                return 1;
+            }
+            eprintln!("Restarting daemon for a protocol version mismatch...");
+            stream = match crate::daemon::restart_and_reconnect(project_root) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("Failed to restart daemon: {}", e);
+                    return 1;
+                }
+            };
+            restarted = true;
+            continue;
+        }
+
+        break (reader, line);
+    };
+
+    let _raw = match RawModeGuard::enable() {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Failed to enter raw terminal mode: {}", e);
+            return 1;
+        }
+    };
+
+    let mut writer = stream.clone();
+
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = match io::stdin().read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            let msg = serde_json::json!({ "kind": "stdin", "data": STANDARD.encode(&buf[..n]) });
+            if writer
+                .write_all(msg.to_string().as_bytes())
+                .and_then(|_| writer.write_all(b"\n"))
+                .is_err()
+            {
+                return;
+            }
+        }
+    });
+
+    // The handshake already consumed one line off the wire; process it
+    // before reading any more.
+    let mut pending = Some(pending_line);
+    loop {
+        let line = match pending.take() {
+            Some(l) => l,
+            None => {
+                let mut line = String::new();
+                match reader.read_line(&mut line) {
+                    Ok(0) => return 0,
+                    Err(_) => return 1,
+                    Ok(_) => {}
+                }
+                line
+            }
+        };
+
+        let msg: serde_json::Value = match serde_json::from_str(line.trim()) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        match msg["kind"].as_str() {
+            Some("stdout") => {
+                if let Some(data) = msg["data"].as_str() {
+                    if let Ok(bytes) = STANDARD.decode(data) {
+                        let _ = io::stdout().write_all(&bytes);
+                        let _ = io::stdout().flush();
+                    }
+                }
+            }
+            Some("response") => {
+                return msg["exitCode"].as_i64().unwrap_or(0) as i32;
+            }
+            _ => {}
+        }
+    }
+}
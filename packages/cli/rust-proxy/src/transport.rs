@@ -0,0 +1,161 @@
+use rustls::{ClientConfig, ClientConnection, StreamOwned};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::daemon::{CONNECT_RETRY_MS, MAX_CONNECT_ATTEMPTS};
+
+/// Anything that can carry the newline-delimited JSON protocol: a local Unix
+/// socket or a remote TCP+TLS stream.
+pub trait Stream: Read + Write + Send {}
+impl<T: Read + Write + Send> Stream for T {}
+
+/// Either independently-lockable read/write halves, or a single stream
+/// shared behind one lock. Split is used whenever the transport can hand out
+/// a genuinely separate handle per direction (e.g. `UnixStream::try_clone`),
+/// so a thread blocked reading never holds up a concurrent writer — that
+/// matters here because the main thread spends most of its time blocked in
+/// `read_line` while the signal-forwarding (chunk0-1) and shell stdin-pump
+/// (chunk0-2) threads need to write at any moment. `rustls::StreamOwned`
+/// can't be split this way, so TLS connections fall back to `Shared`.
+enum Inner {
+    Split {
+        reader: Mutex<Box<dyn Read + Send>>,
+        writer: Mutex<Box<dyn Write + Send>>,
+    },
+    Shared(Mutex<Box<dyn Stream>>),
+}
+
+/// A cloneable handle onto the daemon connection.
+#[derive(Clone)]
+pub struct Connection {
+    inner: Arc<Inner>,
+}
+
+impl Connection {
+    /// Wrap a stream whose reads and writes can't be split into independent
+    /// handles (TLS sessions).
+    pub fn new(stream: Box<dyn Stream>) -> Self {
+        Connection {
+            inner: Arc::new(Inner::Shared(Mutex::new(stream))),
+        }
+    }
+
+    /// Wrap independently-clonable read/write halves of the same stream.
+    pub fn split(reader: Box<dyn Read + Send>, writer: Box<dyn Write + Send>) -> Self {
+        Connection {
+            inner: Arc::new(Inner::Split {
+                reader: Mutex::new(reader),
+                writer: Mutex::new(writer),
+            }),
+        }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &*self.inner {
+            Inner::Split { reader, .. } => reader.lock().unwrap().read(buf),
+            Inner::Shared(stream) => stream.lock().unwrap().read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &*self.inner {
+            Inner::Split { writer, .. } => writer.lock().unwrap().write(buf),
+            Inner::Shared(stream) => stream.lock().unwrap().write(buf),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match &*self.inner {
+            Inner::Split { writer, .. } => writer.lock().unwrap().flush(),
+            Inner::Shared(stream) => stream.lock().unwrap().flush(),
+        }
+    }
+}
+
+/// A parsed `max://host:port` daemon URL, as set via `MAX_DAEMON_URL` or the
+/// project's `max.json` `"daemon"` field.
+pub struct RemoteDaemon {
+    pub host: String,
+    pub port: u16,
+}
+
+impl RemoteDaemon {
+    pub fn parse(url: &str) -> Option<Self> {
+        let rest = url.strip_prefix("max://")?;
+        let (host, port) = rest.rsplit_once(':')?;
+        Some(RemoteDaemon {
+            host: host.to_string(),
+            port: port.parse().ok()?,
+        })
+    }
+}
+
+fn tls_config() -> Result<Arc<ClientConfig>, String> {
+    let mut roots = rustls::RootCertStore::empty();
+
+    if let Ok(ca_path) = std::env::var("MAX_DAEMON_CA") {
+        let pem = std::fs::read(&ca_path)
+            .map_err(|e| format!("Failed to read MAX_DAEMON_CA {}: {}", ca_path, e))?;
+        let certs = rustls_pemfile::certs(&mut pem.as_slice())
+            .map_err(|e| format!("Failed to parse MAX_DAEMON_CA {}: {}", ca_path, e))?;
+        for cert in certs {
+            roots
+                .add(&rustls::Certificate(cert))
+                .map_err(|e| format!("Failed to trust pinned cert: {}", e))?;
+        }
+    } else {
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+    }
+
+    Ok(Arc::new(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    ))
+}
+
+/// Dial a remote daemon over TCP+TLS, retrying with the same backoff as the
+/// local Unix-socket path. Skips all of the local spawn/pid/liveness logic —
+/// that's a Unix-local concern; a remote daemon is someone else's to manage.
+pub fn connect(remote: &RemoteDaemon) -> Result<Connection, String> {
+    let addr = format!("{}:{}", remote.host, remote.port);
+    let config = tls_config()?;
+    let server_name = rustls::ServerName::try_from(remote.host.as_str())
+        .map_err(|e| format!("Invalid daemon host {}: {}", remote.host, e))?;
+
+    for attempt in 0..MAX_CONNECT_ATTEMPTS {
+        match TcpStream::connect(&addr) {
+            Ok(tcp) => {
+                let conn = ClientConnection::new(config.clone(), server_name.clone())
+                    .map_err(|e| format!("TLS setup failed: {}", e))?;
+                let tls = StreamOwned::new(conn, tcp);
+                return Ok(Connection::new(Box::new(tls)));
+            }
+            Err(_) if attempt < MAX_CONNECT_ATTEMPTS - 1 => {
+                thread::sleep(Duration::from_millis(CONNECT_RETRY_MS));
+                continue;
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Failed to connect to {} after {} attempts: {}",
+                    addr, MAX_CONNECT_ATTEMPTS, e
+                ))
+            }
+        }
+    }
+
+    Err(format!("Failed to connect to {}", addr))
+}